@@ -6,10 +6,10 @@ use tracing::{info_span, instrument, Instrument};
 #[instrument(skip_all)]
 pub async fn find_validations(
   acm: aws_sdk_acm::Client,
-  root_domain: &str,
+  validation_root: &str,
   subdomains: &[String],
-) -> Result<Vec<rm::ChangeBatch>> {
-  let mut cbs = Vec::new();
+) -> Result<Vec<rm::Change>> {
+  let mut changes = Vec::new();
 
   let mut certs = acm
     .list_certificates()
@@ -40,32 +40,27 @@ pub async fn find_validations(
         continue;
       }
       let domain = v.domain_name();
-      if subdomains.iter().find(|s| domain.ends_with(*s)).is_none() && domain.ends_with(root_domain) {
+      if subdomains.iter().find(|s| domain.ends_with(*s)).is_none() && domain.ends_with(validation_root) {
         if let Some(rr) = v.resource_record() {
-          let cb = rm::ChangeBatch::builder()
-            .changes(
-              rm::Change::builder()
-                .action(rm::ChangeAction::Upsert)
-                .resource_record_set(
-                  rm::ResourceRecordSet::builder()
-                    .r#type(rr.r#type().as_str().into())
-                    .name(rr.name())
-                    .resource_records(rm::ResourceRecord::builder().value(rr.value()).build().unwrap())
-                    .ttl(86400)
-                    .build()
-                    .unwrap(),
-                )
+          let change = rm::Change::builder()
+            .action(rm::ChangeAction::Upsert)
+            .resource_record_set(
+              rm::ResourceRecordSet::builder()
+                .r#type(rr.r#type().as_str().into())
+                .name(rr.name())
+                .resource_records(rm::ResourceRecord::builder().value(rr.value()).build().unwrap())
+                .ttl(86400)
                 .build()
                 .unwrap(),
             )
             .build()
             .unwrap();
 
-          cbs.push(cb);
+          changes.push(change);
         }
       }
     }
   }
 
-  Ok(cbs)
+  Ok(changes)
 }
@@ -0,0 +1,132 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use aws_sdk_route53::types as rm;
+use axum::{
+  extract::State,
+  http::{header::AUTHORIZATION, Request, StatusCode},
+  middleware::{self, Next},
+  response::{IntoResponse, Response},
+  routing::get,
+  Json, Router,
+};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::{info, info_span, instrument, Instrument};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountStatus {
+  pub id: String,
+  pub environment: Option<String>,
+  pub assumed: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ZoneStatus {
+  pub account: String,
+  pub name: String,
+  pub id: String,
+  pub delegated: bool,
+  pub dnssec_active: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingChange {
+  pub account: String,
+  pub action: String,
+  pub r#type: String,
+  pub name: String,
+}
+
+#[derive(Debug, Default)]
+struct Snapshot {
+  accounts: Vec<AccountStatus>,
+  zones: Vec<ZoneStatus>,
+  changes: Vec<PendingChange>,
+}
+
+/// the controller's in-memory view of the last reconciliation loop, served read-only over the inspection API
+#[derive(Debug, Default)]
+pub struct ApiState(Mutex<Snapshot>);
+
+impl ApiState {
+  pub fn new() -> Arc<ApiState> {
+    Arc::new(ApiState::default())
+  }
+
+  /// clear state ahead of a fresh main loop iteration
+  pub async fn reset(&self) {
+    let mut snapshot = self.0.lock().await;
+    *snapshot = Snapshot::default();
+  }
+
+  pub async fn record_account(&self, account: AccountStatus) {
+    self.0.lock().await.accounts.push(account);
+  }
+
+  pub async fn record_zone(&self, zone: ZoneStatus) {
+    self.0.lock().await.zones.push(zone);
+  }
+
+  pub async fn record_changes(&self, account: &str, changes: &[rm::Change]) {
+    let mut pending: Vec<_> = changes
+      .iter()
+      .map(|change| {
+        let rrs = change.resource_record_set().unwrap();
+
+        PendingChange {
+          account: account.to_string(),
+          action: change.action().as_str().to_string(),
+          r#type: rrs.r#type().as_str().to_string(),
+          name: rrs.name().to_string(),
+        }
+      })
+      .collect();
+
+    self.0.lock().await.changes.append(&mut pending);
+  }
+}
+
+async fn accounts_handler(State(state): State<Arc<ApiState>>) -> impl IntoResponse {
+  Json(state.0.lock().await.accounts.clone())
+}
+
+async fn zones_handler(State(state): State<Arc<ApiState>>) -> impl IntoResponse {
+  Json(state.0.lock().await.zones.clone())
+}
+
+async fn changes_handler(State(state): State<Arc<ApiState>>) -> impl IntoResponse {
+  Json(state.0.lock().await.changes.clone())
+}
+
+async fn require_bearer_token(
+  State(token): State<Arc<String>>,
+  request: Request<axum::body::Body>,
+  next: Next,
+) -> Response {
+  let expected = format!("Bearer {}", token);
+
+  match request.headers().get(AUTHORIZATION) {
+    Some(header) if header.as_bytes() == expected.as_bytes() => next.run(request).await,
+    _ => (StatusCode::UNAUTHORIZED, "missing or invalid bearer token").into_response(),
+  }
+}
+
+/// serve the read-only `/accounts`, `/zones`, and `/changes` inspection endpoints on `addr`, gated behind
+/// a bearer token, until the process exits
+#[instrument(skip_all)]
+pub async fn serve(state: Arc<ApiState>, token: String, addr: SocketAddr) -> Result<(), std::io::Error> {
+  let app = Router::new()
+    .route("/accounts", get(accounts_handler))
+    .route("/zones", get(zones_handler))
+    .route("/changes", get(changes_handler))
+    .with_state(state)
+    .layer(middleware::from_fn_with_state(Arc::new(token), require_bearer_token));
+
+  info!(%addr, "starting inspection api server");
+
+  let listener = tokio::net::TcpListener::bind(addr)
+    .instrument(info_span!("bind inspection api server", %addr))
+    .await?;
+
+  axum::serve(listener, app).await
+}
@@ -1,6 +1,6 @@
-use std::time::Duration;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use aws_sdk_route53::types as rm;
 use aws_config::Region;
 use clap::Parser;
@@ -34,10 +34,128 @@ struct App {
   /// AWS regions to check ACM certificates in. If not specified, only checks the default region
   #[arg(long = "region")]
   regions: Option<Vec<String>>,
+
+  /// also publish a DS record for the delegated sub zone in the root zone, establishing the DNSSEC chain of trust
+  #[arg(long)]
+  dnssec: bool,
+
+  /// template for the expected name of a delegated sub-account zone. Supports `{env}`, `{root}`, and `{account}` placeholders
+  #[arg(long, default_value = "{env}.{root}")]
+  zone_template: ZoneTemplate,
+
+  /// STS external ID to present when assuming the root and sub-account roles, for confused-deputy protection. Repeat
+  /// as `env=value` to scope to a specific environment tier, or pass a bare value as the default for the root role
+  /// and any environment without a specific override
+  #[arg(long = "external-id")]
+  external_ids: Vec<String>,
+
+  /// session name to use when assuming the root and sub-account roles
+  #[arg(long, default_value = "r5d3")]
+  session_name: String,
+
+  /// bind address for the Prometheus `/metrics`, `/healthz`, and `/readyz` endpoints. If not specified, no metrics server is started
+  #[arg(long)]
+  metrics_addr: Option<SocketAddr>,
+
+  /// path to a policy file (see `policy::Policy`) whose rules are evaluated against every pending change before it's applied
+  #[arg(long)]
+  policy_file: Option<PathBuf>,
+
+  /// fail the current loop iteration with an error instead of skipping when a pending change violates a policy rule
+  #[arg(long)]
+  policy_strict: bool,
+
+  /// bind address for the read-only inspection API exposing discovered accounts, zones, and pending changes.
+  /// Requires `--api-token`. If not specified, no inspection API server is started
+  #[arg(long)]
+  api_addr: Option<SocketAddr>,
+
+  /// bearer token required to query the inspection API
+  #[arg(long)]
+  api_token: Option<String>,
+}
+
+/// resolve the external ID to present for `env` (or the root role, when `env` is `None`) out of the repeatable
+/// `--external-id` flag, falling back to a bare (unscoped) value if one was given
+fn resolve_external_id(external_ids: &[String], env: Option<&str>) -> Option<String> {
+  let mut default = None;
+
+  for raw in external_ids {
+    match raw.split_once('=') {
+      Some((k, v)) if Some(k) == env => return Some(v.to_string()),
+      Some(_) => continue,
+      None => default = Some(raw.clone()),
+    }
+  }
+
+  default
+}
+
+/// assume `role_arn`, optionally presenting an STS external ID, returning the resulting SDK config
+async fn assume_role_secured(
+  role_arn: &str,
+  external_id: Option<&str>,
+  session_name: &str,
+  region: Option<Region>,
+) -> aws_config::SdkConfig {
+  let mut provider_builder = aws_config::sts::AssumeRoleProvider::builder(role_arn).session_name(session_name);
+
+  if let Some(external_id) = external_id {
+    provider_builder = provider_builder.external_id(external_id);
+  }
+
+  if let Some(ref region) = region {
+    provider_builder = provider_builder.region(region.clone());
+  }
+
+  let provider = provider_builder.build().await;
+
+  let mut loader = aws_config::from_env().credentials_provider(provider);
+
+  if let Some(region) = region {
+    loader = loader.region(region);
+  }
+
+  loader.load().await
+}
+
+/// a `{env}`/`{root}`/`{account}` interpolation pattern used to name delegated sub-account zones
+#[derive(Debug, Clone)]
+struct ZoneTemplate(String);
+
+impl ZoneTemplate {
+  fn render(&self, env: &str, root: &str, account: &str) -> String {
+    self.0.replace("{env}", env).replace("{root}", root).replace("{account}", account)
+  }
+
+  /// the suffix every zone name rendered from this template ends with for a given `root`, derived from
+  /// the literal template text at and after the `{root}` placeholder (e.g. `{env}.{root}` and
+  /// `{account}.{root}` both resolve to `root` itself, but `{env}.{root}.internal` resolves to
+  /// `{root}.internal` with `{root}` substituted). Used to gate ACM validation discovery to domains that
+  /// genuinely belong to the managed root zone, regardless of how the env/account-specific part is named.
+  /// A template without a `{root}` placeholder falls back to `root` itself, which the names it renders
+  /// need not end with
+  fn validation_root(&self, root: &str) -> String {
+    match self.0.find("{root}") {
+      Some(idx) => self.0[idx..].replace("{root}", root),
+      None => root.to_string(),
+    }
+  }
+}
+
+impl std::str::FromStr for ZoneTemplate {
+  type Err = std::convert::Infallible;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Ok(ZoneTemplate(s.to_string()))
+  }
 }
 
 mod acm;
+mod api;
+mod metrics;
 mod o11y;
+mod policy;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -46,8 +164,45 @@ async fn main() -> Result<()> {
   let app = App::parse();
   debug!("loaded config: {:?}", app);
 
+  let metrics = metrics::Metrics::new();
+  let api_state = api::ApiState::new();
+
+  let policy = app
+    .policy_file
+    .as_ref()
+    .map(|path| policy::Policy::load(path))
+    .transpose()?;
+
+  if let Some(addr) = app.metrics_addr {
+    let metrics = metrics.clone();
+    tokio::spawn(async move {
+      if let Err(e) = metrics::serve(metrics, addr).await {
+        warn!("metrics server exited: {:?}", e);
+      }
+    });
+  }
+
+  match (app.api_addr, &app.api_token) {
+    (Some(addr), Some(token)) => {
+      let api_state = api_state.clone();
+      let token = token.clone();
+      tokio::spawn(async move {
+        if let Err(e) = api::serve(api_state, token, addr).await {
+          warn!("inspection api server exited: {:?}", e);
+        }
+      });
+    }
+    (Some(_), None) => warn!("--api-addr given without --api-token, not starting inspection api"),
+    (None, _) => {}
+  }
+
   loop {
-    main_loop(&app).await?;
+    let start = tokio::time::Instant::now();
+
+    main_loop(&app, &metrics, policy.as_ref(), &api_state).await?;
+
+    metrics.observe_loop_duration(start.elapsed().as_secs_f64());
+    metrics.mark_ready();
 
     if app.once {
       break;
@@ -62,9 +217,13 @@ async fn main() -> Result<()> {
 }
 
 #[instrument(skip_all)]
-async fn main_loop(app: &App) -> Result<()> {
+async fn main_loop(app: &App, metrics: &metrics::Metrics, policy: Option<&policy::Policy>, api_state: &api::ApiState) -> Result<()> {
+  api_state.reset().await;
+
   let root_config = match app.root_role {
-    Some(ref root_role) => control_aws::assume_role(root_role, None).await,
+    Some(ref root_role) => {
+      assume_role_secured(root_role, resolve_external_id(&app.external_ids, None).as_deref(), &app.session_name, None).await
+    }
     None => aws_config::load_from_env().await,
   };
 
@@ -86,12 +245,36 @@ async fn main_loop(app: &App) -> Result<()> {
 
   match control_aws::org::discover_accounts(root_config).await {
     Ok(accounts) => {
+      metrics.set_accounts_discovered(accounts.len() as u64);
+
+      let mut planned = Vec::new();
+
       for acc in accounts {
         let aid = acc.id.clone();
-        work(app, acc, &root_r53, &rid)
-          .instrument(info_span!("work on account", account = aid))
-          .await
-          .expect("failed to work on account");
+
+        if let Some(p) = plan_account(app, acc, &root_r53, &rid, metrics, policy, api_state)
+          .instrument(info_span!("plan account", account = aid))
+          .await?
+        {
+          planned.push(p);
+        }
+      }
+
+      let total_changes: usize = planned.iter().map(|p| p.changes.len()).sum();
+
+      if policy.is_some_and(|policy| policy.blast_radius_tripped(total_changes)) {
+        warn!(
+          total_changes,
+          max = policy.and_then(|policy| policy.max_changes_per_loop),
+          "blast-radius fuse tripped for this loop, applying none of the pending changes"
+        );
+      } else {
+        for p in planned {
+          let aid = p.account.clone();
+          apply_account_changes(app, &root_r53, &rid, metrics, p)
+            .instrument(info_span!("apply account changes", account = aid))
+            .await?;
+        }
       }
     }
     Err(e) => {
@@ -103,31 +286,64 @@ async fn main_loop(app: &App) -> Result<()> {
   Ok(())
 }
 
-async fn work(app: &App, acc: Account, root_r53: &aws_sdk_route53::Client, rid: &String) -> Result<()> {
+/// the changes this controller wants to apply to the root zone on behalf of one sub-account, already past
+/// reconciliation and policy enforcement. Kept separate from actually applying them so [`main_loop`] can
+/// sum every account's changes and check the blast-radius fuse once, before anything is sent to Route53
+struct PlannedChanges {
+  account: String,
+  changes: Vec<rm::Change>,
+}
+
+/// discover `acc`'s delegated zone(s) and pending ACM validation records, reconcile them against what's
+/// already live in the root zone, and run the result through `policy`. Returns `None` when there's nothing
+/// to plan (no environment tag, role couldn't be assumed, or no changes survived reconciliation/policy)
+#[instrument(skip_all)]
+async fn plan_account(
+  app: &App,
+  acc: Account,
+  root_r53: &aws_sdk_route53::Client,
+  rid: &String,
+  metrics: &metrics::Metrics,
+  policy: Option<&policy::Policy>,
+  api_state: &api::ApiState,
+) -> Result<Option<PlannedChanges>> {
   if acc.environment.is_none() {
     info!(account = acc.id, "account has no environment tag, skipping");
-    return Ok(());
+    api_state
+      .record_account(api::AccountStatus { id: acc.id, environment: None, assumed: false })
+      .await;
+    return Ok(None);
   }
 
-  let env = acc.environment.unwrap();
+  let env = acc.environment.clone().unwrap();
 
   let sub_role = format!("arn:aws:iam::{}:role{}", acc.id, app.discover_role);
+  let external_id = resolve_external_id(&app.external_ids, Some(&env));
 
   // ignore non-existing role
-  let sts = aws_sdk_sts::Client::new(&control_aws::assume_role(&sub_role, None).await);
+  let sts = aws_sdk_sts::Client::new(&assume_role_secured(&sub_role, external_id.as_deref(), &app.session_name, None).await);
   match sts.get_caller_identity().send().await {
     Ok(_) => {
       info!(account = acc.id, environment = env, "successfully assumed role");
+      api_state
+        .record_account(api::AccountStatus { id: acc.id.clone(), environment: Some(env.clone()), assumed: true })
+        .await;
     }
     Err(e) => {
       debug!("ignore failed assume role: {:?}", e);
-      return Ok(());
+      metrics.inc_assume_role_failures();
+      api_state
+        .record_account(api::AccountStatus { id: acc.id.clone(), environment: Some(env.clone()), assumed: false })
+        .await;
+      return Ok(None);
     }
   }
 
   let mut subdomains = Vec::new();
+  let mut changes = Vec::new();
 
-  let sub_r53 = aws_sdk_route53::Client::new(&control_aws::assume_role(&sub_role, None).await);
+  let sub_r53 =
+    aws_sdk_route53::Client::new(&assume_role_secured(&sub_role, external_id.as_deref(), &app.session_name, None).await);
   let mut zones = sub_r53
     .list_hosted_zones()
     .into_paginator()
@@ -144,7 +360,9 @@ async fn work(app: &App, acc: Account, root_r53: &aws_sdk_route53::Client, rid:
 
     let zname = zone.name();
 
-    if zname != format!("{}.{}.", env, app.root_domain) {
+    let expected_zname = format!("{}.", app.zone_template.render(&env, &app.root_domain, &acc.id));
+
+    if zname != expected_zname {
       warn!(
         name = zone.name(),
         id = zone.id(),
@@ -171,69 +389,302 @@ async fn work(app: &App, acc: Account, root_r53: &aws_sdk_route53::Client, rid:
       .map(|ns| rm::ResourceRecord::builder().value(ns).build().unwrap())
       .collect();
 
-    let cb = rm::ChangeBatch::builder()
-      .changes(
-        rm::Change::builder()
-          .action(rm::ChangeAction::Upsert)
-          .resource_record_set(
-            rm::ResourceRecordSet::builder()
-              .r#type(rm::RrType::Ns)
-              .name(zname)
-              .set_resource_records(Some(nsrr))
-              .ttl(86400)
-              .build()
-              .unwrap(),
-          )
-          .build()
-          .unwrap(),
-      )
-      .build()
-      .unwrap();
-
-    if app.dry_run {
-      warn!("would upsert NS record: {:?}", &cb);
-    } else {
-      root_r53
-        .change_resource_record_sets()
-        .hosted_zone_id(rid)
-        .change_batch(cb)
+    changes.push(
+      rm::Change::builder()
+        .action(rm::ChangeAction::Upsert)
+        .resource_record_set(
+          rm::ResourceRecordSet::builder()
+            .r#type(rm::RrType::Ns)
+            .name(zname)
+            .set_resource_records(Some(nsrr))
+            .ttl(86400)
+            .build()
+            .unwrap(),
+        )
+        .build()
+        .unwrap(),
+    );
+
+    let mut dnssec_active = None;
+
+    if app.dnssec {
+      let ksks = sub_r53
+        .get_dnssec()
+        .hosted_zone_id(zone.id())
         .send()
-        .instrument(info_span!("upsert NS record"))
+        .instrument(info_span!("get subdomain dnssec status", zone_name = zone.name()))
         .await?;
+
+      let active_ksks: Vec<_> = ksks.key_signing_keys().iter().filter(|ksk| ksk.status() == Some("ACTIVE")).collect();
+
+      if active_ksks.is_empty() {
+        dnssec_active = Some(false);
+        warn!(zone_name = zone.name(), "no active KSK found for zone, skipping DS record");
+      } else {
+        dnssec_active = Some(true);
+
+        // a KSK rotation can leave two KSKs active at once; publish a DS value for every one of them.
+        // an ACTIVE KSK can still be missing its DS payload in-between key-generation steps, so skip
+        // (rather than panic on) any KSK that doesn't have one yet
+        let ds_rr: Vec<_> = active_ksks
+          .iter()
+          .filter_map(|ksk| match ksk.ds_record() {
+            Some(ds) => Some(rm::ResourceRecord::builder().value(ds).build().unwrap()),
+            None => {
+              warn!(zone_name = zone.name(), "active KSK has no ds_record yet, skipping it");
+              None
+            }
+          })
+          .collect();
+
+        if ds_rr.is_empty() {
+          warn!(zone_name = zone.name(), "no active KSK had a usable ds_record, skipping DS record");
+        } else {
+          changes.push(
+            rm::Change::builder()
+              .action(rm::ChangeAction::Upsert)
+              .resource_record_set(
+                rm::ResourceRecordSet::builder()
+                  .r#type(rm::RrType::Ds)
+                  .name(zname)
+                  .set_resource_records(Some(ds_rr))
+                  .ttl(86400)
+                  .build()
+                  .unwrap(),
+              )
+              .build()
+              .unwrap(),
+          );
+        }
+      }
     }
+
+    api_state
+      .record_zone(api::ZoneStatus {
+        account: acc.id.clone(),
+        name: zname.to_string(),
+        id: zone.id().to_string(),
+        delegated: true,
+        dnssec_active,
+      })
+      .await;
   }
 
-  let cbs = if let Some(ref regions) = app.regions {
+  let validation_root = app.zone_template.validation_root(&app.root_domain);
+
+  let validation_changes = if let Some(ref regions) = app.regions {
     let mut ret = Vec::new();
     for r_str in regions {
       let region = Region::new(r_str.clone());
 
-      let sub_acm = aws_sdk_acm::Client::new(&control_aws::assume_role(&sub_role, Some(region)).await);
+      let sub_acm = aws_sdk_acm::Client::new(
+        &assume_role_secured(&sub_role, external_id.as_deref(), &app.session_name, Some(region)).await,
+      );
 
-      let vals = acm::find_validations(sub_acm, &app.root_domain, &subdomains).await?;
+      let vals = acm::find_validations(sub_acm, &validation_root, &subdomains).await?;
       ret.extend(vals);
     }
 
     ret
   } else {
-    let sub_acm = aws_sdk_acm::Client::new(&control_aws::assume_role(&sub_role, None).await);
+    let sub_acm =
+      aws_sdk_acm::Client::new(&assume_role_secured(&sub_role, external_id.as_deref(), &app.session_name, None).await);
 
-    acm::find_validations(sub_acm, &app.root_domain, &subdomains).await?
+    acm::find_validations(sub_acm, &validation_root, &subdomains).await?
   };
 
-  for cb in cbs {
-    if app.dry_run {
-      warn!("would upsert DNS validation record: {:?}", &cb);
-    } else {
-      root_r53
-        .change_resource_record_sets()
-        .hosted_zone_id(rid)
-        .change_batch(cb)
-        .send()
-        .instrument(info_span!("upsert DNS validation records"))
-        .await?;
+  changes.extend(validation_changes);
+
+  let changes = reconcile(root_r53, rid, changes).await?;
+
+  let changes = match policy {
+    Some(policy) => {
+      let enforced = policy::enforce(policy, &acc.id, &env, changes, app.policy_strict);
+
+      if enforced.strict_violation {
+        return Err(anyhow!(
+          "account {} has a change violating policy under --policy-strict, failing this loop iteration",
+          acc.id
+        ));
+      }
+
+      enforced.changes
     }
+    None => changes,
+  };
+
+  api_state.record_changes(&acc.id, &changes).await;
+
+  if changes.is_empty() {
+    debug!(account = acc.id, "no record changes needed, already up to date");
+    return Ok(None);
+  }
+
+  Ok(Some(PlannedChanges { account: acc.id, changes }))
+}
+
+/// apply one account's already-reconciled, already-policy-checked changes to the root zone
+#[instrument(skip_all)]
+async fn apply_account_changes(
+  app: &App,
+  root_r53: &aws_sdk_route53::Client,
+  rid: &str,
+  metrics: &metrics::Metrics,
+  planned: PlannedChanges,
+) -> Result<()> {
+  let mut ns_count = 0u64;
+  let mut ds_count = 0u64;
+  let mut validation_count = 0u64;
+
+  for change in &planned.changes {
+    match change.resource_record_set().unwrap().r#type() {
+      rm::RrType::Ns => ns_count += 1,
+      rm::RrType::Ds => ds_count += 1,
+      _ => validation_count += 1,
+    }
+  }
+
+  let cb = rm::ChangeBatch::builder().set_changes(Some(planned.changes)).build().unwrap();
+
+  if app.dry_run {
+    warn!(account = planned.account, "would apply change batch: {:?}", &cb);
+  } else {
+    root_r53
+      .change_resource_record_sets()
+      .hosted_zone_id(rid)
+      .change_batch(cb)
+      .send()
+      .instrument(info_span!("apply change batch"))
+      .await?;
+
+    metrics.inc_zones_delegated(ns_count);
+    metrics.inc_ds_records_upserted(ds_count);
+    metrics.inc_validation_records_upserted(validation_count);
   }
 
   Ok(())
 }
+
+/// Drop changes whose desired record set already matches what's live in the root zone, so steady-state
+/// loops don't hammer `change_resource_record_sets` for no-op upserts.
+#[instrument(skip_all)]
+async fn reconcile(root_r53: &aws_sdk_route53::Client, rid: &str, changes: Vec<rm::Change>) -> Result<Vec<rm::Change>> {
+  let mut kept = Vec::new();
+  let mut skipped = 0usize;
+
+  for change in changes {
+    let desired = change.resource_record_set().unwrap();
+    let name = desired.name().to_string();
+    let rtype = desired.r#type().clone();
+
+    let existing = root_r53
+      .list_resource_record_sets()
+      .hosted_zone_id(rid)
+      .start_record_name(&name)
+      .start_record_type(rtype.clone())
+      .max_items(1)
+      .send()
+      .instrument(info_span!("list existing record", name = name, r#type = rtype.as_str()))
+      .await?;
+
+    let current = existing
+      .resource_record_sets()
+      .iter()
+      .find(|rr| rr.name() == name && rr.r#type() == &rtype);
+
+    if current.is_some_and(|cur| records_equal(cur, desired)) {
+      debug!(name, r#type = rtype.as_str(), "record already up to date, skipping");
+      skipped += 1;
+    } else {
+      kept.push(change);
+    }
+  }
+
+  info!(applied = kept.len(), skipped, "reconciled pending record changes");
+
+  Ok(kept)
+}
+
+fn records_equal(a: &rm::ResourceRecordSet, b: &rm::ResourceRecordSet) -> bool {
+  if a.ttl() != b.ttl() {
+    return false;
+  }
+
+  let mut av: Vec<_> = a.resource_records().iter().map(|r| r.value()).collect();
+  let mut bv: Vec<_> = b.resource_records().iter().map(|r| r.value()).collect();
+  av.sort_unstable();
+  bv.sort_unstable();
+
+  av == bv
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn resolve_external_id_prefers_env_scoped_value_over_bare_default() {
+    let ids = vec!["dev=dev-id".to_string(), "bare-id".to_string()];
+    assert_eq!(resolve_external_id(&ids, Some("dev")), Some("dev-id".to_string()));
+  }
+
+  #[test]
+  fn resolve_external_id_falls_back_to_bare_default() {
+    let ids = vec!["dev=dev-id".to_string(), "bare-id".to_string()];
+    assert_eq!(resolve_external_id(&ids, Some("prod")), Some("bare-id".to_string()));
+  }
+
+  #[test]
+  fn resolve_external_id_none_when_nothing_matches() {
+    let ids = vec!["dev=dev-id".to_string()];
+    assert_eq!(resolve_external_id(&ids, Some("prod")), None);
+    assert_eq!(resolve_external_id(&ids, None), None);
+  }
+
+  #[test]
+  fn validation_root_resolves_to_bare_root_when_template_root_is_the_tail() {
+    let template: ZoneTemplate = "{env}.{root}".parse().unwrap();
+    assert_eq!(template.validation_root("example.com"), "example.com");
+
+    let template: ZoneTemplate = "{account}.{root}".parse().unwrap();
+    assert_eq!(template.validation_root("example.com"), "example.com");
+  }
+
+  #[test]
+  fn validation_root_keeps_literal_template_text_after_the_placeholder() {
+    let template: ZoneTemplate = "{env}.{root}.internal".parse().unwrap();
+    assert_eq!(template.validation_root("example.com"), "example.com.internal");
+  }
+
+  #[test]
+  fn validation_root_falls_back_to_bare_root_without_a_placeholder() {
+    let template: ZoneTemplate = "static-zone".parse().unwrap();
+    assert_eq!(template.validation_root("example.com"), "example.com");
+  }
+
+  fn record_set(ttl: i64, values: &[&str]) -> rm::ResourceRecordSet {
+    let mut builder = rm::ResourceRecordSet::builder().r#type(rm::RrType::Ns).name("sub.example.com.").ttl(ttl);
+
+    for v in values {
+      builder = builder.resource_records(rm::ResourceRecord::builder().value(*v).build().unwrap());
+    }
+
+    builder.build().unwrap()
+  }
+
+  #[test]
+  fn records_equal_ignores_resource_record_order() {
+    let a = record_set(86400, &["ns1.example.com.", "ns2.example.com."]);
+    let b = record_set(86400, &["ns2.example.com.", "ns1.example.com."]);
+
+    assert!(records_equal(&a, &b));
+  }
+
+  #[test]
+  fn records_equal_detects_ttl_or_value_mismatch() {
+    let a = record_set(86400, &["ns1.example.com."]);
+
+    assert!(!records_equal(&a, &record_set(3600, &["ns1.example.com."])));
+    assert!(!records_equal(&a, &record_set(86400, &["ns2.example.com."])));
+  }
+}
@@ -0,0 +1,121 @@
+use std::{
+  convert::Infallible,
+  net::SocketAddr,
+  sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+  },
+};
+
+use axum::{
+  extract::State,
+  http::StatusCode,
+  response::IntoResponse,
+  routing::get,
+  Router,
+};
+use tracing::{info, info_span, instrument, Instrument};
+
+/// operational counters/gauges for the controller, scraped over HTTP in Prometheus text format
+#[derive(Debug, Default)]
+pub struct Metrics {
+  accounts_discovered: AtomicU64,
+  zones_delegated: AtomicU64,
+  ds_records_upserted: AtomicU64,
+  validation_records_upserted: AtomicU64,
+  assume_role_failures: AtomicU64,
+  loop_duration_seconds: AtomicU64,
+  ready: AtomicBool,
+}
+
+impl Metrics {
+  pub fn new() -> Arc<Metrics> {
+    Arc::new(Metrics::default())
+  }
+
+  pub fn set_accounts_discovered(&self, n: u64) {
+    self.accounts_discovered.store(n, Ordering::Relaxed);
+  }
+
+  pub fn inc_zones_delegated(&self, n: u64) {
+    self.zones_delegated.fetch_add(n, Ordering::Relaxed);
+  }
+
+  pub fn inc_ds_records_upserted(&self, n: u64) {
+    self.ds_records_upserted.fetch_add(n, Ordering::Relaxed);
+  }
+
+  pub fn inc_validation_records_upserted(&self, n: u64) {
+    self.validation_records_upserted.fetch_add(n, Ordering::Relaxed);
+  }
+
+  pub fn inc_assume_role_failures(&self) {
+    self.assume_role_failures.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn observe_loop_duration(&self, seconds: f64) {
+    self.loop_duration_seconds.store(seconds.to_bits(), Ordering::Relaxed);
+  }
+
+  /// mark the controller ready, i.e. it has completed at least one full main loop
+  pub fn mark_ready(&self) {
+    self.ready.store(true, Ordering::Relaxed);
+  }
+
+  fn render(&self) -> String {
+    format!(
+      "# TYPE r5d3_accounts_discovered gauge\nr5d3_accounts_discovered {}\n\
+       # TYPE r5d3_zones_delegated_total counter\nr5d3_zones_delegated_total {}\n\
+       # TYPE r5d3_ds_records_upserted_total counter\nr5d3_ds_records_upserted_total {}\n\
+       # TYPE r5d3_validation_records_upserted_total counter\nr5d3_validation_records_upserted_total {}\n\
+       # TYPE r5d3_assume_role_failures_total counter\nr5d3_assume_role_failures_total {}\n\
+       # TYPE r5d3_loop_duration_seconds gauge\nr5d3_loop_duration_seconds {}\n",
+      self.accounts_discovered.load(Ordering::Relaxed),
+      self.zones_delegated.load(Ordering::Relaxed),
+      self.ds_records_upserted.load(Ordering::Relaxed),
+      self.validation_records_upserted.load(Ordering::Relaxed),
+      self.assume_role_failures.load(Ordering::Relaxed),
+      f64::from_bits(self.loop_duration_seconds.load(Ordering::Relaxed)),
+    )
+  }
+}
+
+async fn metrics_handler(State(metrics): State<Arc<Metrics>>) -> impl IntoResponse {
+  (
+    StatusCode::OK,
+    [("content-type", "text/plain; version=0.0.4")],
+    metrics.render(),
+  )
+}
+
+async fn healthz_handler() -> impl IntoResponse {
+  (StatusCode::OK, "ok")
+}
+
+async fn readyz_handler(State(metrics): State<Arc<Metrics>>) -> impl IntoResponse {
+  if metrics.ready.load(Ordering::Relaxed) {
+    (StatusCode::OK, "ready")
+  } else {
+    (StatusCode::SERVICE_UNAVAILABLE, "not ready")
+  }
+}
+
+/// serve `/metrics`, `/healthz`, and `/readyz` on `addr` until the process exits
+#[instrument(skip_all)]
+pub async fn serve(metrics: Arc<Metrics>, addr: SocketAddr) -> Result<Infallible, std::io::Error> {
+  let app = Router::new()
+    .route("/metrics", get(metrics_handler))
+    .route("/healthz", get(healthz_handler))
+    .route("/readyz", get(readyz_handler))
+    .with_state(metrics);
+
+  info!(%addr, "starting metrics server");
+
+  let listener = tokio::net::TcpListener::bind(addr)
+    .instrument(info_span!("bind metrics server", %addr))
+    .await?;
+
+  axum::serve(listener, app).await?;
+
+  unreachable!("axum::serve only returns on error")
+}
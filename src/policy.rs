@@ -0,0 +1,239 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use aws_sdk_route53::types as rm;
+use serde::Deserialize;
+use tracing::warn;
+
+/// a single named guardrail, evaluated in order against every pending change. The first rule that doesn't
+/// match its constraints denies the change; a change that satisfies every rule's constraints is allowed
+#[derive(Debug, Deserialize)]
+pub struct Rule {
+  pub id: String,
+  #[serde(default)]
+  pub allow_types: Option<Vec<String>>,
+  #[serde(default)]
+  pub deny_types: Option<Vec<String>>,
+  /// only names ending in one of these suffixes may be touched
+  #[serde(default)]
+  pub name_suffixes: Option<Vec<String>>,
+  #[serde(default)]
+  pub min_ttl: Option<i64>,
+  #[serde(default)]
+  pub max_ttl: Option<i64>,
+}
+
+/// guardrails loaded from `--policy-file`
+#[derive(Debug, Deserialize, Default)]
+pub struct Policy {
+  #[serde(default)]
+  pub rules: Vec<Rule>,
+  /// blast-radius fuse: abort the whole batch if more changes than this are pending in a single loop
+  #[serde(default)]
+  pub max_changes_per_loop: Option<usize>,
+}
+
+impl Policy {
+  pub fn load(path: &Path) -> Result<Policy> {
+    let raw = fs::read_to_string(path).with_context(|| format!("reading policy file {:?}", path))?;
+    serde_json::from_str(&raw).with_context(|| format!("parsing policy file {:?}", path))
+  }
+
+  /// whether `total_changes` — the full set of post-policy changes pending across every account in the
+  /// current main loop iteration — trips the blast-radius fuse. Must be checked once against the whole
+  /// loop's total, not per account, and before anything is applied: a misconfigured template churning a
+  /// few records across many accounts must still trip it, and tripping it must abort the whole batch
+  pub fn blast_radius_tripped(&self, total_changes: usize) -> bool {
+    self.max_changes_per_loop.is_some_and(|max| total_changes > max)
+  }
+}
+
+/// a flattened view of a pending `rm::Change`, the shape policy rules are evaluated against
+struct PendingChange<'a> {
+  r#type: String,
+  name: String,
+  ttl: i64,
+  account: &'a str,
+  environment: &'a str,
+}
+
+fn flatten<'a>(change: &rm::Change, account: &'a str, environment: &'a str) -> PendingChange<'a> {
+  let rrs = change.resource_record_set().unwrap();
+
+  PendingChange {
+    r#type: rrs.r#type().as_str().to_string(),
+    name: rrs.name().to_string(),
+    ttl: rrs.ttl().unwrap_or_default(),
+    account,
+    environment,
+  }
+}
+
+/// whether `name` ends with `suffix` at a DNS label boundary, i.e. `name` is exactly `suffix` or the
+/// character immediately before the suffix is a label separator (`.`). A plain `ends_with` would let a
+/// lookalike zone like `evilexample.com.` satisfy a configured suffix of `example.com.`
+fn matches_label_suffix(name: &str, suffix: &str) -> bool {
+  let suffix = suffix.trim_start_matches('.');
+
+  name == suffix || name.ends_with(&format!(".{suffix}"))
+}
+
+fn evaluate<'a>(rules: &'a [Rule], change: &PendingChange) -> Option<&'a Rule> {
+  rules.iter().find(|rule| {
+    if let Some(ref allow) = rule.allow_types {
+      if !allow.iter().any(|t| t.eq_ignore_ascii_case(&change.r#type)) {
+        return true;
+      }
+    }
+
+    if let Some(ref deny) = rule.deny_types {
+      if deny.iter().any(|t| t.eq_ignore_ascii_case(&change.r#type)) {
+        return true;
+      }
+    }
+
+    if let Some(ref suffixes) = rule.name_suffixes {
+      if !suffixes.iter().any(|s| matches_label_suffix(&change.name, s)) {
+        return true;
+      }
+    }
+
+    if let Some(min_ttl) = rule.min_ttl {
+      if change.ttl < min_ttl {
+        return true;
+      }
+    }
+
+    if let Some(max_ttl) = rule.max_ttl {
+      if change.ttl > max_ttl {
+        return true;
+      }
+    }
+
+    false
+  })
+}
+
+/// the result of running a set of changes through [`enforce`]
+pub struct Enforced {
+  /// changes that satisfied every rule
+  pub changes: Vec<rm::Change>,
+  /// whether a rule violation was hit while `strict` was set. The caller is expected to treat this as a
+  /// hard failure of the current loop (propagate an error) rather than silently applying the partial,
+  /// rule-clean `changes` — `--policy-strict` exists precisely so operators can fail loud on a violation
+  pub strict_violation: bool,
+}
+
+/// run `changes` through `policy`, dropping any change that violates a rule. Does not evaluate the
+/// blast-radius fuse — see [`Policy::blast_radius_tripped`], which must be checked once by the caller
+/// against the total across every account, before anything is applied
+pub fn enforce(policy: &Policy, account: &str, environment: &str, changes: Vec<rm::Change>, strict: bool) -> Enforced {
+  let mut kept = Vec::with_capacity(changes.len());
+  let mut strict_violation = false;
+
+  for change in changes {
+    let flattened = flatten(&change, account, environment);
+
+    match evaluate(&policy.rules, &flattened) {
+      Some(rule) => {
+        warn!(
+          rule_id = rule.id,
+          r#type = flattened.r#type,
+          name = flattened.name,
+          account,
+          environment,
+          "change violates policy rule, skipping"
+        );
+
+        if strict {
+          strict_violation = true;
+        }
+      }
+      None => kept.push(change),
+    }
+  }
+
+  Enforced { changes: kept, strict_violation }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn change(r#type: rm::RrType, name: &str, ttl: i64) -> rm::Change {
+    rm::Change::builder()
+      .action(rm::ChangeAction::Upsert)
+      .resource_record_set(rm::ResourceRecordSet::builder().r#type(r#type).name(name).ttl(ttl).build().unwrap())
+      .build()
+      .unwrap()
+  }
+
+  #[test]
+  fn name_suffix_rule_requires_label_boundary() {
+    let rules = vec![Rule {
+      id: "root-only".to_string(),
+      allow_types: None,
+      deny_types: None,
+      name_suffixes: Some(vec!["example.com.".to_string()]),
+      min_ttl: None,
+      max_ttl: None,
+    }];
+
+    let legit = flatten(&change(rm::RrType::Ns, "sub.example.com.", 3600), "acc", "dev");
+    assert!(evaluate(&rules, &legit).is_none());
+
+    let lookalike = flatten(&change(rm::RrType::Ns, "evilexample.com.", 3600), "acc", "dev");
+    assert!(evaluate(&rules, &lookalike).is_some());
+
+    let exact = flatten(&change(rm::RrType::Ns, "example.com.", 3600), "acc", "dev");
+    assert!(evaluate(&rules, &exact).is_none());
+  }
+
+  #[test]
+  fn evaluate_requires_every_rule_constraint_to_pass() {
+    let rules = vec![Rule {
+      id: "ttl-bounds".to_string(),
+      allow_types: None,
+      deny_types: None,
+      name_suffixes: None,
+      min_ttl: Some(300),
+      max_ttl: None,
+    }];
+
+    let too_low = flatten(&change(rm::RrType::Ns, "sub.example.com.", 60), "acc", "dev");
+    assert!(evaluate(&rules, &too_low).is_some());
+
+    let ok = flatten(&change(rm::RrType::Ns, "sub.example.com.", 600), "acc", "dev");
+    assert!(evaluate(&rules, &ok).is_none());
+  }
+
+  #[test]
+  fn enforce_marks_strict_violation_but_still_returns_rule_clean_changes() {
+    let policy = Policy {
+      rules: vec![Rule {
+        id: "root-only".to_string(),
+        allow_types: None,
+        deny_types: None,
+        name_suffixes: Some(vec!["example.com.".to_string()]),
+        min_ttl: None,
+        max_ttl: None,
+      }],
+      max_changes_per_loop: None,
+    };
+
+    let changes = vec![change(rm::RrType::Ns, "sub.example.com.", 3600), change(rm::RrType::Ns, "evilexample.com.", 3600)];
+
+    let enforced = enforce(&policy, "111111111111", "dev", changes, true);
+
+    assert_eq!(enforced.changes.len(), 1);
+    assert!(enforced.strict_violation);
+  }
+
+  #[test]
+  fn blast_radius_tripped_when_total_exceeds_max() {
+    let policy = Policy { rules: vec![], max_changes_per_loop: Some(10) };
+
+    assert!(!policy.blast_radius_tripped(10));
+    assert!(policy.blast_radius_tripped(11));
+  }
+}